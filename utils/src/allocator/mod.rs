@@ -6,13 +6,36 @@ use allocator_api2::alloc::{AllocError, Allocator};
 pub use allocator_api2::vec::Vec;
 use std::{
     alloc::{GlobalAlloc, System, Layout},
-    sync::atomic::{AtomicUsize, Ordering},
-    ptr::NonNull,
-    slice,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    ptr::{self, NonNull},
 };
 
 static ALLOC: AtomicUsize = AtomicUsize::new(0);
 static DEALLOC: AtomicUsize = AtomicUsize::new(0);
+static ALLOC_COUNT: AtomicUsize = AtomicUsize::new(0);
+static DEALLOC_COUNT: AtomicUsize = AtomicUsize::new(0);
+static REALLOC_COUNT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// Power-of-two size-class upper bounds for the allocation histogram, from `<=16` up
+/// to `<=1 MiB`; anything larger falls into a final overflow bucket.
+const HISTOGRAM_THRESHOLDS: [usize; 17] = [
+    16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536, 131072, 262144,
+    524288, 1048576,
+];
+const HISTOGRAM_BUCKETS: usize = HISTOGRAM_THRESHOLDS.len() + 1;
+
+static HISTOGRAM_ENABLED: AtomicBool = AtomicBool::new(false);
+// Used only to seed the array repeat expressions below; each element is independent.
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO_COUNT: AtomicUsize = AtomicUsize::new(0);
+static HISTOGRAM_COUNTS: [AtomicUsize; HISTOGRAM_BUCKETS] = [ZERO_COUNT; HISTOGRAM_BUCKETS];
+static HISTOGRAM_LIVE: [AtomicUsize; HISTOGRAM_BUCKETS] = [ZERO_COUNT; HISTOGRAM_BUCKETS];
+
+/// Returns the index of the size class `size` falls into.
+fn histogram_bucket(size: usize) -> usize {
+    HISTOGRAM_THRESHOLDS.iter().position(|&threshold| size <= threshold).unwrap_or(HISTOGRAM_BUCKETS - 1)
+}
 
 /// A custom allocator that tracks memory allocations and deallocations.
 #[derive(Debug, Copy, Clone, Default)]
@@ -23,16 +46,92 @@ impl TrackingAllocator {
     pub fn reset() {
         ALLOC.store(0, Ordering::SeqCst);
         DEALLOC.store(0, Ordering::SeqCst);
+        ALLOC_COUNT.store(0, Ordering::SeqCst);
+        DEALLOC_COUNT.store(0, Ordering::SeqCst);
+        REALLOC_COUNT.store(0, Ordering::SeqCst);
+        PEAK.store(0, Ordering::SeqCst);
+        for bucket in &HISTOGRAM_COUNTS {
+            bucket.store(0, Ordering::SeqCst);
+        }
+        for bucket in &HISTOGRAM_LIVE {
+            bucket.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// Enables or disables the opt-in allocation-size histogram. Disabled by default
+    /// since it adds a bucket lookup and extra atomic ops to every allocate (two) and
+    /// deallocate (one).
+    pub fn set_histogram_enabled(enabled: bool) {
+        HISTOGRAM_ENABLED.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Returns the current per-size-class allocation count and live bytes. All buckets
+    /// read zero unless histogram tracking was enabled via `set_histogram_enabled(true)`.
+    pub fn histogram() -> std::vec::Vec<HistogramBucket> {
+        (0..HISTOGRAM_BUCKETS)
+            .map(|i| HistogramBucket {
+                max_size: HISTOGRAM_THRESHOLDS.get(i).copied(),
+                count: HISTOGRAM_COUNTS[i].load(Ordering::SeqCst),
+                live_bytes: HISTOGRAM_LIVE[i].load(Ordering::SeqCst),
+            })
+            .collect()
     }
 
     /// Records an allocation of a given size.
     pub fn record_alloc(layout: Layout) {
         ALLOC.fetch_add(layout.size(), Ordering::SeqCst);
+        ALLOC_COUNT.fetch_add(1, Ordering::SeqCst);
+        Self::update_peak();
+        if HISTOGRAM_ENABLED.load(Ordering::SeqCst) {
+            let bucket = histogram_bucket(layout.size());
+            HISTOGRAM_COUNTS[bucket].fetch_add(1, Ordering::SeqCst);
+            HISTOGRAM_LIVE[bucket].fetch_add(layout.size(), Ordering::SeqCst);
+        }
     }
 
     /// Records a deallocation of a given size.
     pub fn record_dealloc(layout: Layout) {
         DEALLOC.fetch_add(layout.size(), Ordering::SeqCst);
+        DEALLOC_COUNT.fetch_add(1, Ordering::SeqCst);
+        if HISTOGRAM_ENABLED.load(Ordering::SeqCst) {
+            let bucket = histogram_bucket(layout.size());
+            HISTOGRAM_LIVE[bucket].fetch_sub(layout.size(), Ordering::SeqCst);
+        }
+    }
+
+    /// Records a reallocation from `old_layout` to `new_layout`, crediting only the
+    /// net byte delta to `alloc`/`dealloc` so growing a buffer in place isn't
+    /// double-counted as a fresh allocation plus a deallocation of the old one.
+    pub fn record_realloc(old_layout: Layout, new_layout: Layout) {
+        REALLOC_COUNT.fetch_add(1, Ordering::SeqCst);
+        let old_size = old_layout.size();
+        let new_size = new_layout.size();
+        if new_size >= old_size {
+            ALLOC.fetch_add(new_size - old_size, Ordering::SeqCst);
+        } else {
+            DEALLOC.fetch_add(old_size - new_size, Ordering::SeqCst);
+        }
+        Self::update_peak();
+        if HISTOGRAM_ENABLED.load(Ordering::SeqCst) {
+            let old_bucket = histogram_bucket(old_size);
+            let new_bucket = histogram_bucket(new_size);
+            HISTOGRAM_LIVE[old_bucket].fetch_sub(old_size, Ordering::SeqCst);
+            HISTOGRAM_COUNTS[new_bucket].fetch_add(1, Ordering::SeqCst);
+            HISTOGRAM_LIVE[new_bucket].fetch_add(new_size, Ordering::SeqCst);
+        }
+    }
+
+    /// Recomputes the current resident size (`alloc - dealloc`) and bumps the
+    /// peak high-water mark if it has grown.
+    fn update_peak() {
+        let current = ALLOC.load(Ordering::SeqCst).saturating_sub(DEALLOC.load(Ordering::SeqCst));
+        let mut peak = PEAK.load(Ordering::SeqCst);
+        while current > peak {
+            match PEAK.compare_exchange_weak(peak, current, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => break,
+                Err(observed) => peak = observed,
+            }
+        }
     }
 
     /// Retrieves the current memory statistics.
@@ -41,15 +140,139 @@ impl TrackingAllocator {
         let dealloc = DEALLOC.load(Ordering::SeqCst);
         let diff = (alloc as isize) - (dealloc as isize);
 
-        Stats { alloc, dealloc, diff }
+        Stats {
+            alloc,
+            dealloc,
+            diff,
+            alloc_count: ALLOC_COUNT.load(Ordering::SeqCst),
+            dealloc_count: DEALLOC_COUNT.load(Ordering::SeqCst),
+            realloc_count: REALLOC_COUNT.load(Ordering::SeqCst),
+            peak: PEAK.load(Ordering::SeqCst),
+            resident: 0,
+        }
+    }
+
+    /// Retrieves memory statistics from jemalloc directly, trading the cheap layout
+    /// accounting of `stats()` for accurate, RSS-backed numbers. Requires the
+    /// `jemalloc` feature, and that jemalloc is *also* installed as the process's
+    /// `#[global_allocator]` (e.g. via the `tikv-jemallocator` crate) — this feature
+    /// only gives you a client for jemalloc's own stats, it does not install jemalloc.
+    /// Without that, these numbers describe an idle, unused jemalloc instance rather
+    /// than the program's real allocations. See `jemalloc::stats` for the (debug-only)
+    /// check this makes for that precondition.
+    #[cfg(feature = "jemalloc")]
+    pub fn jemalloc_stats() -> Stats {
+        jemalloc::stats()
     }
 }
 
 /// Memory usage statistics for the allocator.
+#[derive(Debug, Clone, Copy, Default)]
 pub struct Stats {
     pub alloc: usize,
     pub dealloc: usize,
     pub diff: isize,
+    /// Number of `allocate` calls observed.
+    pub alloc_count: usize,
+    /// Number of `deallocate` calls observed.
+    pub dealloc_count: usize,
+    /// Number of `grow`/`shrink` (reallocation) calls observed.
+    pub realloc_count: usize,
+    /// Peak resident size (`alloc - dealloc`) observed so far.
+    pub peak: usize,
+    /// True resident memory in bytes, as reported by jemalloc's `stats.resident`.
+    /// Zero unless populated by the `jemalloc` feature's backend, since the default
+    /// layout-accounting backend has no way to observe allocator rounding, thread-cache
+    /// retention, or fragmentation.
+    pub resident: usize,
+}
+
+/// One size-class bucket of the allocation-size histogram (see `TrackingAllocator::histogram`).
+#[derive(Debug, Clone, Copy)]
+pub struct HistogramBucket {
+    /// Upper bound (inclusive) of this bucket's size class, in bytes, or `None` for
+    /// the overflow bucket covering everything larger than the largest threshold.
+    pub max_size: Option<usize>,
+    /// Number of allocations observed in this size class, counting both `allocate`
+    /// calls and reallocations whose new size lands in it.
+    pub count: usize,
+    /// Bytes currently live (allocated but not yet deallocated) in this size class.
+    pub live_bytes: usize,
+}
+
+/// A scoped snapshot of the allocator's statistics, useful for measuring the
+/// memory cost of a block of code without manually diffing `TrackingAllocator::stats()`
+/// calls taken before and after.
+pub struct Region {
+    baseline: Stats,
+    #[cfg_attr(not(feature = "jemalloc"), allow(dead_code))]
+    jemalloc: bool,
+}
+
+impl Region {
+    /// Captures the current allocator statistics as the baseline for this region.
+    pub fn new() -> Self {
+        Self { baseline: TrackingAllocator::stats(), jemalloc: false }
+    }
+
+    /// Captures a jemalloc-backed baseline instead, so `change`/`change_and_reset`
+    /// report true resident memory rather than requested-layout totals. Requires the
+    /// `jemalloc` feature, and that jemalloc is *also* installed as the process's
+    /// `#[global_allocator]` (see `TrackingAllocator::jemalloc_stats`) — otherwise the
+    /// baseline and every `change()` off of it will be near-zero noise.
+    #[cfg(feature = "jemalloc")]
+    pub fn new_jemalloc() -> Self {
+        Self { baseline: jemalloc::stats(), jemalloc: true }
+    }
+
+    /// Takes a fresh snapshot from whichever backend this region was created with.
+    fn snapshot(&self) -> Stats {
+        #[cfg(feature = "jemalloc")]
+        if self.jemalloc {
+            return jemalloc::stats();
+        }
+        TrackingAllocator::stats()
+    }
+
+    /// Returns the change in statistics since this region was created (or last reset),
+    /// without disturbing the baseline. `peak` is not diffed since it is a high-water
+    /// mark rather than a cumulative counter; it reflects the peak resident size
+    /// observed globally, which may predate this region.
+    ///
+    /// The other fields use `saturating_sub` rather than plain subtraction: a
+    /// jemalloc-backed region's `alloc` tracks a live gauge rather than a monotonic
+    /// cumulative sum (see `jemalloc::stats`), and `TrackingAllocator::reset()` can
+    /// also drop the global counters below an outstanding region's baseline. Either
+    /// case would otherwise underflow; saturating to zero is the honest answer since
+    /// the true delta can no longer be recovered. `diff` is unaffected since it is
+    /// already a signed net-resident figure, not a cumulative counter.
+    pub fn change(&self) -> Stats {
+        let current = self.snapshot();
+        Stats {
+            alloc: current.alloc.saturating_sub(self.baseline.alloc),
+            dealloc: current.dealloc.saturating_sub(self.baseline.dealloc),
+            diff: current.diff - self.baseline.diff,
+            alloc_count: current.alloc_count.saturating_sub(self.baseline.alloc_count),
+            dealloc_count: current.dealloc_count.saturating_sub(self.baseline.dealloc_count),
+            realloc_count: current.realloc_count.saturating_sub(self.baseline.realloc_count),
+            peak: current.peak,
+            resident: current.resident.saturating_sub(self.baseline.resident),
+        }
+    }
+
+    /// Returns the change in statistics since this region was created (or last reset),
+    /// then rebaselines the region to the current statistics.
+    pub fn change_and_reset(&mut self) -> Stats {
+        let delta = self.change();
+        self.baseline = self.snapshot();
+        delta
+    }
+}
+
+impl Default for Region {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 unsafe impl Allocator for TrackingAllocator {
@@ -60,7 +283,7 @@ unsafe impl Allocator for TrackingAllocator {
             if ptr.is_null() {
                 Err(AllocError)
             } else {
-                let slice_ptr: *mut [u8] = slice::from_raw_parts_mut(ptr, layout.size());
+                let slice_ptr: *mut [u8] = ptr::slice_from_raw_parts_mut(ptr, layout.size());
                 let non_null_slice: NonNull<[u8]> = NonNull::new_unchecked(slice_ptr);
                 Self::record_alloc(layout);
 
@@ -75,4 +298,456 @@ unsafe impl Allocator for TrackingAllocator {
         let raw_ptr: *mut u8 = ptr.as_ptr();
         System.dealloc(raw_ptr, layout);
     }
+
+    /// Grows an allocation in place via `System.realloc` when the alignment allows it,
+    /// falling back to allocate+copy+deallocate otherwise, and records the net byte
+    /// delta rather than double-counting a full allocation and deallocation.
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(new_layout.size() >= old_layout.size());
+        let raw = realloc_raw(ptr, old_layout, new_layout)?;
+        Self::record_realloc(old_layout, new_layout);
+        let slice_ptr: *mut [u8] = ptr::slice_from_raw_parts_mut(raw.as_ptr(), new_layout.size());
+        Ok(NonNull::new_unchecked(slice_ptr))
+    }
+
+    /// Same as `grow`, but zeroes the newly extended bytes.
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(new_layout.size() >= old_layout.size());
+        let raw = realloc_raw(ptr, old_layout, new_layout)?;
+        Self::record_realloc(old_layout, new_layout);
+        raw.as_ptr().add(old_layout.size()).write_bytes(0, new_layout.size() - old_layout.size());
+        let slice_ptr: *mut [u8] = ptr::slice_from_raw_parts_mut(raw.as_ptr(), new_layout.size());
+        Ok(NonNull::new_unchecked(slice_ptr))
+    }
+
+    /// Shrinks an allocation in place via `System.realloc` when the alignment allows it,
+    /// falling back to allocate+copy+deallocate otherwise, and records the net byte
+    /// delta rather than double-counting a full allocation and deallocation.
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(new_layout.size() <= old_layout.size());
+        let raw = realloc_raw(ptr, old_layout, new_layout)?;
+        Self::record_realloc(old_layout, new_layout);
+        let slice_ptr: *mut [u8] = ptr::slice_from_raw_parts_mut(raw.as_ptr(), new_layout.size());
+        Ok(NonNull::new_unchecked(slice_ptr))
+    }
+}
+
+/// A `GlobalAlloc` wrapper that feeds the same counters as `TrackingAllocator`, so
+/// whole-program allocations (inside `std`, third-party crates, `Box`, etc.) can be
+/// profiled, not just collections that opt in via the `Allocator` trait. Install it with:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOC: TrackingGlobalAlloc = TrackingGlobalAlloc::system();
+/// ```
+///
+/// and then read `TrackingAllocator::stats()` or take a `Region` as usual; both
+/// front-ends share one set of counters, so mixed usage composes.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TrackingGlobalAlloc<A = System> {
+    inner: A,
+}
+
+impl<A> TrackingGlobalAlloc<A> {
+    /// Wraps `inner`, tracking every allocation made through it.
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+impl TrackingGlobalAlloc<System> {
+    /// Wraps the system allocator, tracking every allocation made through it.
+    pub const fn system() -> Self {
+        Self { inner: System }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingGlobalAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            TrackingAllocator::record_alloc(layout);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        TrackingAllocator::record_dealloc(layout);
+        self.inner.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+            TrackingAllocator::record_realloc(layout, new_layout);
+        }
+        new_ptr
+    }
+}
+
+/// Resizes `ptr` from `old_layout` to `new_layout` using `System.realloc` in place when
+/// alignment is unchanged, or falls back to allocate+copy+deallocate when it isn't
+/// (`System.realloc` cannot change alignment).
+unsafe fn realloc_raw(
+    ptr: NonNull<u8>,
+    old_layout: Layout,
+    new_layout: Layout,
+) -> Result<NonNull<u8>, AllocError> {
+    let raw = if new_layout.align() == old_layout.align() {
+        System.realloc(ptr.as_ptr(), old_layout, new_layout.size())
+    } else {
+        let new_ptr = System.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr, old_layout.size().min(new_layout.size()));
+            System.dealloc(ptr.as_ptr(), old_layout);
+        }
+        new_ptr
+    };
+
+    NonNull::new(raw).ok_or(AllocError)
+}
+
+/// An alternative stats backend that queries jemalloc's own counters instead of
+/// summing requested `Layout` sizes, so `alloc`/`resident` reflect allocator rounding,
+/// thread-cache retention, and fragmentation rather than a best-effort estimate.
+///
+/// This module only talks to whatever jemalloc instance is statically linked in via
+/// `tikv-jemalloc-ctl`'s `sys` dependency; it does not install jemalloc as anything.
+/// The caller is responsible for separately making jemalloc the process's
+/// `#[global_allocator]` (typically via the `tikv-jemallocator` crate) — otherwise
+/// `std` allocations go through the system allocator instead, and the stats below
+/// describe an idle, unused jemalloc rather than the program's real memory use.
+#[cfg(feature = "jemalloc")]
+mod jemalloc {
+    use super::Stats;
+    use std::sync::OnceLock;
+
+    /// Advances jemalloc's stats epoch and reads `stats.allocated`/`stats.resident`
+    /// into a `Stats` snapshot. The epoch advance is what makes the read reflect
+    /// allocations made since the last call, rather than a stale cached value.
+    pub fn stats() -> Stats {
+        warn_if_not_global_allocator();
+
+        tikv_jemalloc_ctl::epoch::advance().expect("failed to advance jemalloc stats epoch");
+
+        let allocated = tikv_jemalloc_ctl::stats::allocated::read()
+            .expect("failed to read jemalloc stats.allocated");
+        let resident = tikv_jemalloc_ctl::stats::resident::read()
+            .expect("failed to read jemalloc stats.resident");
+
+        Stats { alloc: allocated, diff: allocated as isize, resident, ..Default::default() }
+    }
+
+    /// Best-effort, debug-only sanity check for the precondition documented on this
+    /// module: allocates a 1 MiB probe buffer through the ordinary global allocator
+    /// and confirms jemalloc's own `stats.allocated` grew by roughly that much. If
+    /// jemalloc is merely linked in but not installed as `#[global_allocator]`, the
+    /// probe allocation goes through the system allocator instead and this stays
+    /// flat, which is exactly the misconfiguration this is meant to catch. Runs at
+    /// most once per process; compiled out entirely in release builds.
+    fn warn_if_not_global_allocator() {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        static CHECKED: OnceLock<()> = OnceLock::new();
+        CHECKED.get_or_init(|| {
+            let read_allocated = || {
+                tikv_jemalloc_ctl::epoch::advance().expect("failed to advance jemalloc stats epoch");
+                tikv_jemalloc_ctl::stats::allocated::read()
+                    .expect("failed to read jemalloc stats.allocated")
+            };
+            const PROBE_SIZE: usize = 1 << 20;
+            let before = read_allocated();
+            let probe: std::vec::Vec<u8> = vec![0u8; PROBE_SIZE];
+            let after = read_allocated();
+            drop(probe);
+            debug_assert!(
+                after >= before + PROBE_SIZE,
+                "jemalloc stats.allocated did not grow after a 1 MiB allocation; jemalloc is \
+                 linked in but does not appear to be installed as the #[global_allocator] (see \
+                 the `tikv-jemallocator` crate), so these stats would not reflect real program \
+                 memory"
+            );
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Installs jemalloc as this test binary's global allocator so the jemalloc-backed
+    // tests below exercise a real, populated jemalloc instance rather than an idle one
+    // (see the precondition documented on `jemalloc::stats`).
+    #[cfg(feature = "jemalloc")]
+    #[global_allocator]
+    static JEMALLOC: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+    // The allocator's counters are process-global statics, so tests that touch them
+    // must not run concurrently with each other.
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn with_clean_state<T>(f: impl FnOnce() -> T) -> T {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        TrackingAllocator::reset();
+        TrackingAllocator::set_histogram_enabled(false);
+        let result = f();
+        TrackingAllocator::set_histogram_enabled(false);
+        result
+    }
+
+    #[test]
+    fn tracks_alloc_and_dealloc_counts() {
+        with_clean_state(|| {
+            let layout = Layout::from_size_align(8, 8).unwrap();
+            let ptr = TrackingAllocator.allocate(layout).unwrap().cast::<u8>();
+            unsafe { TrackingAllocator.deallocate(ptr, layout) };
+
+            let stats = TrackingAllocator::stats();
+            assert_eq!(stats.alloc_count, 1);
+            assert_eq!(stats.dealloc_count, 1);
+            assert_eq!(stats.realloc_count, 0);
+        });
+    }
+
+    #[test]
+    fn peak_tracks_high_water_mark_and_survives_deallocation() {
+        with_clean_state(|| {
+            let small = Layout::from_size_align(16, 8).unwrap();
+            let big = Layout::from_size_align(256, 8).unwrap();
+
+            let a = TrackingAllocator.allocate(big).unwrap().cast::<u8>();
+            let b = TrackingAllocator.allocate(small).unwrap().cast::<u8>();
+            unsafe { TrackingAllocator.deallocate(a, big) };
+
+            let stats = TrackingAllocator::stats();
+            assert_eq!(stats.peak, 256 + 16);
+            assert_eq!(stats.diff, 16);
+
+            unsafe { TrackingAllocator.deallocate(b, small) };
+        });
+    }
+
+    #[test]
+    fn region_change_reports_alloc_and_dealloc() {
+        with_clean_state(|| {
+            let layout = Layout::from_size_align(64, 8).unwrap();
+            let region = Region::new();
+
+            let ptr = TrackingAllocator.allocate(layout).unwrap().cast::<u8>();
+            let delta = region.change();
+            assert_eq!(delta.alloc, 64);
+            assert_eq!(delta.dealloc, 0);
+            assert_eq!(delta.diff, 64);
+
+            unsafe { TrackingAllocator.deallocate(ptr, layout) };
+            let delta = region.change();
+            assert_eq!(delta.alloc, 64);
+            assert_eq!(delta.dealloc, 64);
+            assert_eq!(delta.diff, 0);
+        });
+    }
+
+    #[test]
+    fn region_change_reports_negative_diff_when_net_freeing() {
+        with_clean_state(|| {
+            let layout = Layout::from_size_align(128, 8).unwrap();
+            let ptr = TrackingAllocator.allocate(layout).unwrap().cast::<u8>();
+            let region = Region::new();
+
+            unsafe { TrackingAllocator.deallocate(ptr, layout) };
+            assert_eq!(region.change().diff, -128);
+        });
+    }
+
+    #[test]
+    fn region_change_and_reset_rebaselines() {
+        with_clean_state(|| {
+            let layout = Layout::from_size_align(16, 8).unwrap();
+            let mut region = Region::new();
+
+            let ptr = TrackingAllocator.allocate(layout).unwrap().cast::<u8>();
+            let first = region.change_and_reset();
+            assert_eq!(first.alloc, 16);
+
+            unsafe { TrackingAllocator.deallocate(ptr, layout) };
+            let second = region.change();
+            assert_eq!(second.alloc, 0);
+            assert_eq!(second.dealloc, 16);
+        });
+    }
+
+    #[test]
+    fn region_change_after_global_reset_saturates_instead_of_underflowing() {
+        with_clean_state(|| {
+            let layout = Layout::from_size_align(32, 8).unwrap();
+            let _ptr = TrackingAllocator.allocate(layout).unwrap();
+            let region = Region::new();
+
+            // A reset() while a Region is outstanding would otherwise make the next
+            // change() subtract a larger baseline from a smaller current value.
+            TrackingAllocator::reset();
+            let delta = region.change();
+            assert_eq!(delta.alloc, 0);
+            assert_eq!(delta.dealloc, 0);
+        });
+    }
+
+    #[test]
+    fn grow_accounts_only_the_net_byte_delta() {
+        with_clean_state(|| {
+            let old_layout = Layout::from_size_align(32, 8).unwrap();
+            let new_layout = Layout::from_size_align(96, 8).unwrap();
+            let ptr = TrackingAllocator.allocate(old_layout).unwrap().cast::<u8>();
+            let region = Region::new();
+
+            let grown =
+                unsafe { TrackingAllocator.grow(ptr, old_layout, new_layout).unwrap() }.cast::<u8>();
+            let delta = region.change();
+            assert_eq!(delta.alloc, 64); // 96 - 32, not 96 (alloc) + 32 (dealloc)
+            assert_eq!(delta.dealloc, 0);
+            assert_eq!(delta.realloc_count, 1);
+
+            unsafe { TrackingAllocator.deallocate(grown, new_layout) };
+        });
+    }
+
+    #[test]
+    fn shrink_accounts_only_the_net_byte_delta() {
+        with_clean_state(|| {
+            let old_layout = Layout::from_size_align(128, 8).unwrap();
+            let new_layout = Layout::from_size_align(48, 8).unwrap();
+            let ptr = TrackingAllocator.allocate(old_layout).unwrap().cast::<u8>();
+            let region = Region::new();
+
+            let shrunk = unsafe { TrackingAllocator.shrink(ptr, old_layout, new_layout).unwrap() }
+                .cast::<u8>();
+            let delta = region.change();
+            assert_eq!(delta.alloc, 0);
+            assert_eq!(delta.dealloc, 80); // 128 - 48
+            assert_eq!(delta.realloc_count, 1);
+
+            unsafe { TrackingAllocator.deallocate(shrunk, new_layout) };
+        });
+    }
+
+    #[test]
+    fn grow_zeroed_zeroes_only_the_extended_tail() {
+        with_clean_state(|| {
+            let old_layout = Layout::from_size_align(8, 8).unwrap();
+            let new_layout = Layout::from_size_align(32, 8).unwrap();
+            let ptr = TrackingAllocator.allocate(old_layout).unwrap().cast::<u8>();
+            unsafe { ptr.as_ptr().write_bytes(0xAB, 8) };
+
+            let grown =
+                unsafe { TrackingAllocator.grow_zeroed(ptr, old_layout, new_layout).unwrap() };
+            let bytes = unsafe { std::slice::from_raw_parts(grown.cast::<u8>().as_ptr(), 32) };
+            assert_eq!(&bytes[..8], &[0xAB; 8]);
+            assert_eq!(&bytes[8..], &[0u8; 24]);
+
+            unsafe { TrackingAllocator.deallocate(grown.cast(), new_layout) };
+        });
+    }
+
+    #[test]
+    fn grow_across_alignment_change_falls_back_and_preserves_data() {
+        with_clean_state(|| {
+            let old_layout = Layout::from_size_align(8, 8).unwrap();
+            let new_layout = Layout::from_size_align(64, 64).unwrap();
+            let ptr = TrackingAllocator.allocate(old_layout).unwrap().cast::<u8>();
+            unsafe { ptr.as_ptr().write_bytes(0x42, 8) };
+
+            let grown = unsafe { TrackingAllocator.grow(ptr, old_layout, new_layout).unwrap() };
+            let bytes = unsafe { std::slice::from_raw_parts(grown.cast::<u8>().as_ptr(), 8) };
+            assert_eq!(bytes, &[0x42; 8]);
+
+            unsafe { TrackingAllocator.deallocate(grown.cast(), new_layout) };
+        });
+    }
+
+    #[test]
+    fn record_realloc_moves_histogram_attribution_to_the_new_bucket() {
+        with_clean_state(|| {
+            TrackingAllocator::set_histogram_enabled(true);
+
+            let old_layout = Layout::from_size_align(8, 8).unwrap(); // <=16 bucket
+            let new_layout = Layout::from_size_align(2000, 8).unwrap(); // <=2048 bucket
+            let ptr = TrackingAllocator.allocate(old_layout).unwrap().cast::<u8>();
+            let grown = unsafe { TrackingAllocator.grow(ptr, old_layout, new_layout).unwrap() };
+
+            let histogram = TrackingAllocator::histogram();
+            let old_bucket = histogram.iter().find(|b| b.max_size == Some(16)).unwrap();
+            let new_bucket = histogram.iter().find(|b| b.max_size == Some(2048)).unwrap();
+            assert_eq!(old_bucket.live_bytes, 0, "grow must release the old bucket's live bytes");
+            assert_eq!(new_bucket.live_bytes, 2000);
+            assert_eq!(new_bucket.count, 1);
+
+            unsafe { TrackingAllocator.deallocate(grown.cast(), new_layout) };
+        });
+    }
+
+    #[test]
+    fn tracking_global_alloc_shares_counters_with_tracking_allocator() {
+        with_clean_state(|| {
+            let alloc = TrackingGlobalAlloc::system();
+            let layout = Layout::from_size_align(64, 8).unwrap();
+
+            let ptr = unsafe { alloc.alloc(layout) };
+            assert!(!ptr.is_null());
+            assert_eq!(TrackingAllocator::stats().alloc, 64);
+            assert_eq!(TrackingAllocator::stats().alloc_count, 1);
+
+            let new_layout = Layout::from_size_align(128, 8).unwrap();
+            let grown = unsafe { alloc.realloc(ptr, layout, new_layout.size()) };
+            assert!(!grown.is_null());
+            assert_eq!(TrackingAllocator::stats().alloc, 128); // 64 + (128 - 64) net delta
+            assert_eq!(TrackingAllocator::stats().realloc_count, 1);
+
+            unsafe { alloc.dealloc(grown, new_layout) };
+            assert_eq!(TrackingAllocator::stats().dealloc, 128);
+        });
+    }
+
+    #[cfg(feature = "jemalloc")]
+    #[test]
+    fn jemalloc_stats_reflects_real_allocations() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let before = TrackingAllocator::jemalloc_stats().alloc;
+        let probe: std::vec::Vec<u8> = vec![0u8; 1 << 20];
+        let after = TrackingAllocator::jemalloc_stats().alloc;
+        assert!(after >= before + (1 << 20));
+
+        drop(probe);
+    }
+
+    #[cfg(feature = "jemalloc")]
+    #[test]
+    fn region_new_jemalloc_tracks_allocated_growth() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let region = Region::new_jemalloc();
+        let probe: std::vec::Vec<u8> = vec![0u8; 1 << 20];
+        let delta = region.change();
+        assert!(delta.alloc >= 1 << 20);
+
+        drop(probe);
+    }
 }